@@ -0,0 +1,68 @@
+use nannou::geom::{Point2, Rect};
+use nannou::math::map_range;
+use nannou::rand::rngs::SmallRng;
+use nannou::rand::Rng;
+
+// discard this many leading iterations so the point cloud has settled onto
+// the attractor before we start recording it
+const BURN_IN: usize = 500;
+
+#[derive(Clone, Copy)]
+pub enum Attractor {
+    DeJong { a: f64, b: f64, c: f64, d: f64 },
+    Clifford { a: f64, b: f64, c: f64, d: f64 },
+}
+
+impl Attractor {
+    pub fn random_de_jong(rng: &mut SmallRng) -> Self {
+        Attractor::DeJong {
+            a: rng.gen_range(-3.0, 3.0),
+            b: rng.gen_range(-3.0, 3.0),
+            c: rng.gen_range(-3.0, 3.0),
+            d: rng.gen_range(-3.0, 3.0),
+        }
+    }
+
+    pub fn random_clifford(rng: &mut SmallRng) -> Self {
+        Attractor::Clifford {
+            a: rng.gen_range(-2.0, 2.0),
+            b: rng.gen_range(-2.0, 2.0),
+            c: rng.gen_range(-2.0, 2.0),
+            d: rng.gen_range(-2.0, 2.0),
+        }
+    }
+
+    fn step(&self, x: f64, y: f64) -> (f64, f64) {
+        match *self {
+            Attractor::DeJong { a, b, c, d } => {
+                ((a * y).sin() - (b * x).cos(), (c * x).sin() - (d * y).cos())
+            }
+            Attractor::Clifford { a, b, c, d } => (
+                (a * y).sin() + c * (a * x).cos(),
+                (b * x).sin() + d * (b * y).cos(),
+            ),
+        }
+    }
+}
+
+// iterates from a fixed start point and scales the attractor's roughly
+// [-2, 2] range into bounds
+pub fn generate(attractor: Attractor, n_steps: usize, bounds: Rect) -> Vec<Point2<f32>> {
+    let (mut x, mut y) = (0.1, 0.1);
+    let mut points = Vec::with_capacity(n_steps.saturating_sub(BURN_IN));
+
+    for i in 0..n_steps {
+        let (nx, ny) = attractor.step(x, y);
+        x = nx;
+        y = ny;
+
+        if i >= BURN_IN {
+            points.push(Point2::new(
+                map_range(x as f32, -2.0, 2.0, bounds.left(), bounds.right()),
+                map_range(y as f32, -2.0, 2.0, bounds.bottom(), bounds.top()),
+            ));
+        }
+    }
+
+    points
+}
@@ -0,0 +1,72 @@
+use nannou::rand::rngs::SmallRng;
+use nannou::rand::Rng;
+
+// classic doom-fire intensities run 0..=36
+const MAX_INTENSITY: u8 = 36;
+
+// bottom row is kept lit; each step every cell above it inherits the cell
+// below with a little random decay and horizontal drift
+pub struct DoomFire {
+    width: usize,
+    height: usize,
+    intensity: Vec<u8>,
+}
+
+impl DoomFire {
+    pub fn new(width: usize, height: usize) -> Self {
+        let mut intensity = vec![0u8; width * height];
+        for x in 0..width {
+            intensity[(height - 1) * width + x] = MAX_INTENSITY;
+        }
+
+        DoomFire {
+            width,
+            height,
+            intensity,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn intensity_at(&self, x: usize, y: usize) -> u8 {
+        self.intensity[y * self.width + x]
+    }
+
+    // decay_prob is the chance a cell loses one unit of intensity as it
+    // propagates up, rather than none
+    pub fn step(&mut self, rng: &mut SmallRng, decay_prob: f32) {
+        for y in 0..self.height - 1 {
+            for x in 0..self.width {
+                let src = self.intensity[(y + 1) * self.width + x];
+                if src == 0 {
+                    self.intensity[y * self.width + x] = 0;
+                    continue;
+                }
+
+                let decay = if rng.gen::<f32>() < decay_prob { 1 } else { 0 };
+                let drift: i32 = rng.gen_range(-1, 2);
+                let dst_x = (x as i32 + drift).clamp(0, self.width as i32 - 1) as usize;
+
+                self.intensity[y * self.width + dst_x] = src.saturating_sub(decay);
+            }
+        }
+    }
+}
+
+// walks a gradient built from colors in the order given: dark at zero, then
+// the first entry, up to the last entry at MAX_INTENSITY
+pub fn gradient_color(intensity: u8, colors: &[[u8; 3]]) -> [u8; 3] {
+    if intensity == 0 {
+        return [0, 0, 0];
+    }
+
+    let t = intensity as f32 / MAX_INTENSITY as f32;
+    let i = ((t * (colors.len() - 1) as f32).round() as usize).min(colors.len() - 1);
+    colors[i]
+}
@@ -7,11 +7,35 @@ use nannou::noise::*;
 use nannou::prelude::*;
 use nannou::rand::rngs::SmallRng;
 use nannou::rand::{Rng, SeedableRng};
+mod attractors;
+mod doom_fire;
 mod linspace;
+use attractors::Attractor;
+use doom_fire::DoomFire;
 use linspace::*;
 
 type Noise = Perlin;
 const SPLINE_DEGREE: usize = 4;
+const FIRE_GRID_W: usize = 80;
+const FIRE_GRID_H: usize = 40;
+const FIRE_DECAY_PROB: f32 = 0.4;
+// frame ranges below are spelled out as plain consts (rather than arithmetic
+// on the previous range) since match range patterns only accept bare
+// literals or const paths, not expressions
+// doom-fire background, simulated before the iris is drawn on top of it
+const FIRE_START: usize = 1;
+const FIRE_END: usize = 100;
+// iris detail
+const IRIS_START: usize = FIRE_END + 1;
+const IRIS_END: usize = 300;
+// frame on which the pupil (and its spline detail) is drawn
+const PUPIL_FRAME: usize = IRIS_END + 1;
+// strange-attractor point cloud, drawn in slices
+const ATTRACTOR_START: usize = PUPIL_FRAME + 1;
+const ATTRACTOR_FRAMES: usize = 60;
+// frame on which the high-res texture is snapshotted and saved
+const FINAL_FRAME: usize = PUPIL_FRAME + ATTRACTOR_FRAMES;
+const ATTRACTOR_STEPS: usize = 3_000_000;
 const COLORS: [[u8; 3]; 5] = [
     [52, 64, 77],
     [85, 101, 115],
@@ -39,6 +63,12 @@ struct Model {
     texture_reshaper: wgpu::TextureReshaper,
     // The RNG (seeded for reproducibility)
     rng: SmallRng,
+    // Dense point cloud from a strange attractor, drawn in slices across
+    // the attractor frames.
+    attractor_points: Vec<Point2<f32>>,
+    attractor_color: Rgba8,
+    // Doom-fire intensity field composited under the iris.
+    fire: DoomFire,
 }
 
 fn model(app: &App) -> Model {
@@ -96,9 +126,24 @@ fn model(app: &App) -> Model {
     // I'm doing this because my integrated graphic chip can't handle all the vertices in a single
     // frame render so I have to build things up in multiple frames.
     app.set_loop_mode(LoopMode::NTimes {
-        number_of_updates: 202,
+        number_of_updates: FINAL_FRAME + 1,
     });
 
+    let mut rng = SmallRng::seed_from_u64(38274903);
+
+    let min_dim = (texture_size[0] as f32).min(texture_size[1] as f32);
+    let attractor = if rng.gen() {
+        Attractor::random_de_jong(&mut rng)
+    } else {
+        Attractor::random_clifford(&mut rng)
+    };
+    let attractor_points = attractors::generate(
+        attractor,
+        ATTRACTOR_STEPS,
+        geom::Rect::from_w_h(min_dim * 0.9, min_dim * 0.9),
+    );
+    let attractor_color = color_to_rgba8(COLORS[rng.gen_range(0, 5) as usize], 0.02);
+
     Model {
         noise: Perlin::new(),
         texture: texture,
@@ -106,7 +151,10 @@ fn model(app: &App) -> Model {
         renderer: renderer,
         texture_capturer: texture_capturer,
         texture_reshaper: texture_reshaper,
-        rng: SmallRng::seed_from_u64(38274903),
+        rng,
+        attractor_points,
+        attractor_color,
+        fire: DoomFire::new(FIRE_GRID_W, FIRE_GRID_H),
     }
 }
 
@@ -192,6 +240,43 @@ fn color_to_rgba8(arr: [u8; 3], alpha: f32) -> Rgba8 {
     rgba8(arr[0], arr[1], arr[2], (alpha as f32 * 255.0) as u8)
 }
 
+// steps the fire and draws it as a grid of rects, masked to the annulus
+// between the pupil and the outer edge
+fn draw_doom_fire(model: &mut Model, min_dim: f32) {
+    model.fire.step(&mut model.rng, FIRE_DECAY_PROB);
+
+    let outer_r = min_dim * 0.5 * 0.75;
+    let inner_r = min_dim * 0.5 * 0.25;
+    let (grid_w, grid_h) = (model.fire.width(), model.fire.height());
+    let cell_w = (outer_r * 2.0) / grid_w as f32;
+    let cell_h = (outer_r * 2.0) / grid_h as f32;
+
+    for y in 0..grid_h {
+        for x in 0..grid_w {
+            let intensity = model.fire.intensity_at(x, y);
+            if intensity == 0 {
+                continue;
+            }
+
+            let px = (x as f32 + 0.5) * cell_w - outer_r;
+            // grid row 0 is the top of the fire, so flip it onto the y-up canvas
+            let py = outer_r - (y as f32 + 0.5) * cell_h;
+            let r = (px * px + py * py).sqrt();
+            if r < inner_r || r > outer_r {
+                continue;
+            }
+
+            let color = doom_fire::gradient_color(intensity, &COLORS);
+            model
+                .draw
+                .rect()
+                .x_y(px, py)
+                .w_h(cell_w, cell_h)
+                .color(color_to_rgba8(color, 0.05));
+        }
+    }
+}
+
 fn update(app: &App, model: &mut Model, _update: Update) {
     // First, reset the `draw` state.
     model.draw.reset();
@@ -225,7 +310,14 @@ fn update(app: &App, model: &mut Model, _update: Update) {
                 color_to_rgba8(COLORS[0], 0.02),
             );
         }
-        1..=200 => {
+        FIRE_START..=FIRE_END => {
+            /*
+             * Doom-fire background, composited under the iris and confined
+             * to the iris annulus.
+             */
+            draw_doom_fire(model, min_dim);
+        }
+        IRIS_START..=IRIS_END => {
             /*
              * Iris
              */
@@ -245,7 +337,7 @@ fn update(app: &App, model: &mut Model, _update: Update) {
                 color,
             );
         }
-        201 => {
+        PUPIL_FRAME => {
             /*
              * Pupil
              */
@@ -271,6 +363,26 @@ fn update(app: &App, model: &mut Model, _update: Update) {
                 rgba(1.0, 1.0, 1.0, 0.02),
             );
         }
+        ATTRACTOR_START..=FINAL_FRAME => {
+            /*
+             * Strange-attractor point field, drawn a slice per frame so we
+             * never hand the whole multi-million-point cloud to the GPU at
+             * once.
+             */
+            let slice_idx = nth - ATTRACTOR_START;
+            let n_points = model.attractor_points.len();
+            let chunk = (n_points + ATTRACTOR_FRAMES - 1) / ATTRACTOR_FRAMES;
+            let start = (slice_idx * chunk).min(n_points);
+            let end = (start + chunk).min(n_points);
+
+            model
+                .draw
+                .point_mode()
+                .polyline()
+                .color(model.attractor_color)
+                .stroke_weight(0.0)
+                .points(model.attractor_points[start..end].to_vec());
+        }
         _ => {}
     };
 
@@ -284,7 +396,7 @@ fn update(app: &App, model: &mut Model, _update: Update) {
         .renderer
         .render_to_texture(device, &mut encoder, &model.draw, &model.texture);
 
-    if nth == 201 {
+    if nth == FINAL_FRAME {
         // Take a snapshot of the texture. The capturer will do the following:
         //
         // 1. Resolve the texture to a non-multisampled texture if necessary.
@@ -299,7 +411,7 @@ fn update(app: &App, model: &mut Model, _update: Update) {
 
         // Save the high-res version once we have completed the draw
         println!("nth = {}", nth);
-        if nth == 201 {
+        if nth == FINAL_FRAME {
             snapshot
                 .read(move |result| {
                     let image = result.expect("failed to map texture memory").to_owned();
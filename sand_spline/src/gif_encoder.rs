@@ -0,0 +1,61 @@
+use gif::{Encoder, Frame, Repeat};
+use nannou::image::RgbaImage;
+use std::fs::File;
+
+// GIF needs an indexed palette, so each frame is quantized to the sketch's
+// flat COLORS set (plus background) by nearest-colour matching
+pub struct GifWriter {
+    encoder: Encoder<File>,
+    width: u16,
+    height: u16,
+}
+
+impl GifWriter {
+    pub fn new(path: &str, width: u16, height: u16, loop_forever: bool, palette: &[[u8; 3]]) -> Self {
+        let flat_palette: Vec<u8> = palette.iter().flat_map(|c| c.iter().copied()).collect();
+        let file = File::create(path).expect("failed to create gif file");
+        let mut encoder = Encoder::new(file, width, height, &flat_palette)
+            .expect("failed to create gif encoder");
+        // a loop count of 0 also means "forever" in a gif, so a play-once gif
+        // simply leaves the repeat extension out
+        if loop_forever {
+            encoder
+                .set_repeat(Repeat::Infinite)
+                .expect("failed to set gif loop count");
+        }
+
+        GifWriter {
+            encoder,
+            width,
+            height,
+        }
+    }
+
+    // delay is in centiseconds, the unit the GIF spec itself uses
+    pub fn push_frame(&mut self, image: &RgbaImage, palette: &[[u8; 3]], delay: u16) {
+        let indices: Vec<u8> = image
+            .pixels()
+            .map(|pixel| nearest_color_index(pixel.0, palette))
+            .collect();
+
+        let mut frame = Frame::from_indexed_pixels(self.width, self.height, &indices, None);
+        frame.delay = delay;
+        self.encoder
+            .write_frame(&frame)
+            .expect("failed to write gif frame");
+    }
+}
+
+fn nearest_color_index(rgba: [u8; 4], palette: &[[u8; 3]]) -> u8 {
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, c)| {
+            let dr = rgba[0] as i32 - c[0] as i32;
+            let dg = rgba[1] as i32 - c[1] as i32;
+            let db = rgba[2] as i32 - c[2] as i32;
+            dr * dr + dg * dg + db * db
+        })
+        .map(|(i, _)| i as u8)
+        .unwrap_or(0)
+}
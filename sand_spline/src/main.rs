@@ -4,14 +4,37 @@ use nannou::noise::*;
 use nannou::prelude::*;
 use nannou::rand::rngs::SmallRng;
 use nannou::rand::{Rng, SeedableRng};
+use nannou_egui::{self, egui, Egui};
+mod gif_encoder;
 mod linspace;
 use bspline::BSpline;
+use gif_encoder::GifWriter;
 use linspace::linspace;
 
 type Noise = Perlin;
 const SPLINE_DEGREE: usize = 4;
-const OUT_DIR: &str = "frames";
 const NFRAMES: usize = 1800;
+const GIF_PATH: &str = "sand_spline.gif";
+const GIF_FPS: u16 = 24;
+// number of gif captures allowed in flight before we block to drain the oldest
+const CAPTURE_PIPELINE_DEPTH: usize = 2;
+const BACKGROUND: [u8; 3] = [236, 230, 220];
+const COLORS: [[u8; 3]; 5] = [
+    [58, 42, 34],
+    [122, 77, 53],
+    [181, 136, 99],
+    [214, 178, 140],
+    [90, 58, 46],
+];
+// background plus the stippling palette, used as the GIF's indexed colour table
+const GIF_PALETTE: [[u8; 3]; 6] = [
+    BACKGROUND,
+    COLORS[0],
+    COLORS[1],
+    COLORS[2],
+    COLORS[3],
+    COLORS[4],
+];
 
 // see the scipy documentation for the constraints on the number of knots etc.
 // https://docs.scipy.org/doc/scipy/reference/generated/scipy.interpolate.BSpline.html
@@ -24,6 +47,54 @@ fn set_knots(domain: (f32, f32), degree: usize, npoints: usize) -> Vec<f32> {
     knots
 }
 
+// each iteration replaces segment (P_i, P_{i+1}) with 0.75*P_i+0.25*P_{i+1}
+// and 0.25*P_i+0.75*P_{i+1}; closed rings wrap the last segment to the first
+fn chaikin_smooth(points: &[Point2<f32>], closed: bool, iterations: usize) -> Vec<Point2<f32>> {
+    let mut points = points.to_vec();
+
+    for _ in 0..iterations {
+        let n = points.len();
+        let n_segments = if closed { n } else { n - 1 };
+        let mut refined = Vec::with_capacity(n_segments * 2);
+
+        if !closed {
+            refined.push(points[0]);
+        }
+        for i in 0..n_segments {
+            let p0 = points[i];
+            let p1 = points[(i + 1) % n];
+            refined.push(p0 * 0.75 + p1 * 0.25);
+            refined.push(p0 * 0.25 + p1 * 0.75);
+        }
+        if !closed {
+            refined.push(points[n - 1]);
+        }
+
+        points = refined;
+    }
+
+    points
+}
+
+fn polyline_point(points: &[Point2<f32>], t: f32, closed: bool) -> Point2<f32> {
+    let n = points.len();
+    let t = if closed {
+        t.rem_euclid(n as f32)
+    } else {
+        t.min((n - 1) as f32)
+    };
+    let i = t.floor() as usize;
+    let j = if closed { (i + 1) % n } else { (i + 1).min(n - 1) };
+    let frac = t - t.floor();
+    points[i] * (1.0 - frac) + points[j] * frac
+}
+
+#[derive(Clone, Copy)]
+enum CurveMode {
+    BSpline,
+    Chaikin { iterations: usize },
+}
+
 fn gen_circle_points(n_control_points: usize, radius: f32) -> Vec<Point2<f32>> {
     let mut shape_points: Vec<_> = geom::Ellipse {
         rect: geom::Rect::from_w_h(radius, radius),
@@ -42,97 +113,448 @@ fn gen_circle_points(n_control_points: usize, radius: f32) -> Vec<Point2<f32>> {
     shape_points
 }
 
+const N_CONTROL_POINTS: usize = 10;
+const CHAIKIN_ITERATIONS: usize = 4;
+
+// sums `octaves` layers of noise, each weighted by gain^o at freq*lacunarity^o,
+// normalized back to a single octave's range
+fn fbm(noise: &Noise, p: [f64; 3], freq: f64, octaves: usize, lacunarity: f32, gain: f32) -> f32 {
+    let mut sum = 0.0;
+    let mut amplitude = 1.0;
+    let mut total_amplitude = 0.0;
+    let mut freq = freq;
+
+    for _ in 0..octaves {
+        sum += amplitude * noise.get([p[0] * freq, p[1] * freq, p[2]]) as f32;
+        total_amplitude += amplitude;
+        amplitude *= gain;
+        freq *= lacunarity as f64;
+    }
+
+    sum / total_amplitude
+}
+
+// offsets p by a second fbm evaluation before sampling when warp_strength > 0
+fn warped_fbm(model: &Model, p: [f64; 3]) -> f32 {
+    let p = if model.warp_strength > 0.0 {
+        let warp_x = fbm(&model.noise, p, 1.0, model.octaves, model.lacunarity, model.gain);
+        let warp_y = fbm(
+            &model.noise,
+            [p[0] + 19.19, p[1] + 7.13, p[2]],
+            1.0,
+            model.octaves,
+            model.lacunarity,
+            model.gain,
+        );
+        [
+            p[0] + (model.warp_strength * warp_x) as f64,
+            p[1] + (model.warp_strength * warp_y) as f64,
+            p[2],
+        ]
+    } else {
+        p
+    };
+
+    fbm(&model.noise, p, 1.0, model.octaves, model.lacunarity, model.gain)
+}
+
+fn pick_color(model: &Model, rng: &mut SmallRng) -> Rgba8 {
+    let active: Vec<usize> = (0..COLORS.len())
+        .filter(|&i| model.colors_active[i])
+        .collect();
+    let i = if active.is_empty() {
+        0
+    } else {
+        active[rng.gen_range(0, active.len())]
+    };
+    let c = COLORS[i];
+    rgba8(c[0], c[1], c[2], (model.color_alpha * 255.0) as u8)
+}
+
 fn draw_spline(model: &Model, draw: &Draw) {
-    let shape = gen_circle_points(10, model.radius);
+    let shape = gen_circle_points(N_CONTROL_POINTS, model.radius);
     let knots = set_knots((0.0, shape.len() as f32), SPLINE_DEGREE, shape.len());
 
     let mut rng = model.rng.to_owned();
 
     for i_line in 0..model.n_lines {
+        let color = pick_color(model, &mut rng);
+
         let mut points = Vec::new(); // NOTE: need to make a new vec each time as it is moved to bspline below
         for p in shape.as_slice() {
-            let dx = model.noise.get([
-                p.x as f64,
-                p.y as f64,
-                model.offset + 0.001 * (i_line * 2) as f64,
-            ]) as f32;
-            let dy = model.noise.get([
-                p.x as f64,
-                p.y as f64,
-                -model.offset + 0.001 * (i_line * 2 + 1) as f64,
-            ]) as f32;
+            let dx = warped_fbm(
+                model,
+                [
+                    p.x as f64,
+                    p.y as f64,
+                    model.offset + 0.001 * (i_line * 2) as f64,
+                ],
+            );
+            let dy = warped_fbm(
+                model,
+                [
+                    p.x as f64,
+                    p.y as f64,
+                    -model.offset + 0.001 * (i_line * 2 + 1) as f64,
+                ],
+            );
             points.push(*p + pt2(dx, dy) * model.magnitude);
         }
 
-        let spline = BSpline::new(SPLINE_DEGREE, points, knots.clone());
-        let knot_domain = spline.knot_domain();
-        let knot_range = knot_domain.1 - knot_domain.0;
-
-        draw.point_mode()
-            .polyline()
-            .color(model.color)
-            .stroke_weight(0.0)
-            .points((0..model.n_grains).map(|p| {
-                spline.point((p as f32 / model.n_grains as f32) * knot_range + knot_domain.0)
-                    + pt2(rng.gen::<f32>(), rng.gen::<f32>()) * 1.5
-            }));
+        match model.curve_mode {
+            CurveMode::BSpline => {
+                // the trailing points are a duplicated wrap of the leading
+                // ones so the open-uniform knot vector still closes the loop
+                let spline = BSpline::new(SPLINE_DEGREE, points, knots.clone());
+                let knot_domain = spline.knot_domain();
+                let knot_range = knot_domain.1 - knot_domain.0;
+
+                draw.point_mode()
+                    .polyline()
+                    .color(color)
+                    .stroke_weight(0.0)
+                    .points((0..model.n_grains).map(|p| {
+                        spline.point((p as f32 / model.n_grains as f32) * knot_range + knot_domain.0)
+                            + pt2(rng.gen::<f32>(), rng.gen::<f32>()) * 1.5
+                    }));
+            }
+            CurveMode::Chaikin { iterations } => {
+                // drop the bspline wrap duplication: chaikin closes the ring itself
+                let refined = chaikin_smooth(&points[..N_CONTROL_POINTS], true, iterations);
+                let n = refined.len() as f32;
+
+                draw.point_mode()
+                    .polyline()
+                    .color(color)
+                    .stroke_weight(0.0)
+                    .points((0..model.n_grains).map(|p| {
+                        polyline_point(&refined, (p as f32 / model.n_grains as f32) * n, true)
+                            + pt2(rng.gen::<f32>(), rng.gen::<f32>()) * 1.5
+                    }));
+            }
+        }
     }
 }
 
 struct Model {
     noise: Noise,
     rng: SmallRng,
+    seed: u64,
     radius: f32,
     n_lines: usize,
     n_grains: usize,
     magnitude: f32,
-    color: Srgba,
+    colors_active: [bool; COLORS.len()],
+    color_alpha: f32,
     offset: f64,
+    // once the user drags the offset slider, stop overwriting it with the
+    // automatic animation curve
+    offset_user_set: bool,
+    curve_mode: CurveMode,
+    octaves: usize,
+    lacunarity: f32,
+    gain: f32,
+    warp_strength: f32,
+    egui: Egui,
+    render_high_res: bool,
+    // rendered once per frame, then both reshaped into the window and fed to
+    // the gif encoder, rather than drawing the spline twice
+    texture: wgpu::Texture,
+    draw: nannou::Draw,
+    renderer: nannou::draw::Renderer,
+    texture_reshaper: wgpu::TextureReshaper,
+    texture_capturer: wgpu::TextureCapturer,
+    // in-flight gif captures, oldest first; drained once CAPTURE_PIPELINE_DEPTH
+    // is exceeded and flushed on exit
+    pending_gif_frames: std::collections::VecDeque<std::sync::mpsc::Receiver<nannou::image::RgbaImage>>,
+    gif: GifWriter,
 }
 
+const DEFAULT_SEED: u64 = 6382987;
+
 fn model(app: &App) -> Model {
-    let win = app
-        .window(app.new_window().view(view).build().unwrap())
+    let window_id = app
+        .new_window()
+        .view(view)
+        .raw_event(raw_window_event)
+        .build()
         .unwrap();
+    let window = app.window(window_id).unwrap();
+    let egui = Egui::from_window(&window);
+
     app.set_loop_mode(LoopMode::NTimes {
         number_of_updates: NFRAMES,
     });
 
-    let out_dir = std::path::Path::new(OUT_DIR);
-    if !out_dir.exists() {
-        std::fs::create_dir(out_dir).expect("Failed to create 'frames' directory.");
-    }
+    let (w, h) = window.rect().w_h();
+
+    let device = window.swap_chain_device();
+    let sample_count = window.msaa_samples();
+    let texture = wgpu::TextureBuilder::new()
+        .size([w as u32, h as u32])
+        .usage(wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED)
+        .sample_count(sample_count)
+        .format(wgpu::TextureFormat::Rgba16Float)
+        .build(device);
+    let draw = nannou::Draw::new();
+    let descriptor = texture.descriptor();
+    let renderer =
+        nannou::draw::RendererBuilder::new().build_from_texture_descriptor(device, descriptor);
+    let texture_view = texture.view().build();
+    let texture_sample_type = texture.sample_type();
+    let dst_format = Frame::TEXTURE_FORMAT;
+    let texture_reshaper = wgpu::TextureReshaper::new(
+        device,
+        &texture_view,
+        sample_count,
+        texture_sample_type,
+        sample_count,
+        dst_format,
+    );
+    let texture_capturer = wgpu::TextureCapturer::default();
 
-    let (w, h) = win.rect().w_h();
-    let color = rgba(0.0, 0.0, 0.0, 0.01);
+    let gif = GifWriter::new(GIF_PATH, w as u16, h as u16, true, &GIF_PALETTE);
 
     Model {
         noise: Noise::new(),
-        rng: SmallRng::seed_from_u64(6382987),
+        rng: SmallRng::seed_from_u64(DEFAULT_SEED),
+        seed: DEFAULT_SEED,
         radius: w.min(h) * 0.4,
         n_lines: 1000,
         n_grains: 8000,
         magnitude: 300.0,
-        color,
+        colors_active: [true; COLORS.len()],
+        color_alpha: 0.01,
         offset: 1.0,
+        offset_user_set: false,
+        curve_mode: CurveMode::Chaikin {
+            iterations: CHAIKIN_ITERATIONS,
+        },
+        octaves: 3,
+        lacunarity: 2.0,
+        gain: 0.5,
+        warp_strength: 0.0,
+        egui,
+        render_high_res: false,
+        texture,
+        draw,
+        renderer,
+        texture_reshaper,
+        texture_capturer,
+        pending_gif_frames: std::collections::VecDeque::new(),
+        gif,
+    }
+}
+
+fn raw_window_event(_app: &App, model: &mut Model, event: &nannou::winit::event::WindowEvent) {
+    model.egui.handle_raw_event(event);
+}
+
+fn update(app: &App, model: &mut Model, update: Update) {
+    // drive the automatic 1800-frame render until the user takes the offset
+    // slider over for themselves
+    if !model.offset_user_set {
+        let nth = app.elapsed_frames() as f32;
+        model.offset = ease::sine::ease_in_out(nth as f64, 1.0, 4.0, NFRAMES as f64);
+    }
+
+    // re-seed so tweaking a slider mid-session still gives a reproducible result
+    model.rng = SmallRng::seed_from_u64(model.seed);
+
+    let egui = &mut model.egui;
+    egui.set_elapsed_time(update.since_start);
+    let ctx = egui.begin_frame();
+
+    egui::Window::new("sand_spline").show(&ctx, |ui| {
+        ui.label("n_lines");
+        ui.add(egui::Slider::new(&mut model.n_lines, 10..=5000));
+        ui.label("n_grains");
+        ui.add(egui::Slider::new(&mut model.n_grains, 100..=20_000));
+        ui.label("magnitude");
+        ui.add(egui::Slider::new(&mut model.magnitude, 0.0..=600.0));
+        ui.label("offset");
+        if ui
+            .add(egui::Slider::new(&mut model.offset, -4.0..=4.0))
+            .changed()
+        {
+            model.offset_user_set = true;
+        }
+        ui.label("radius");
+        ui.add(egui::Slider::new(&mut model.radius, 10.0..=600.0));
+
+        ui.label("noise seed");
+        let mut seed = model.seed;
+        if ui.add(egui::Slider::new(&mut seed, 0..=1_000_000)).changed() {
+            model.seed = seed;
+        }
+
+        ui.separator();
+        ui.label("colors");
+        for (i, rgb) in COLORS.iter().enumerate() {
+            ui.checkbox(&mut model.colors_active[i], format!("{:?}", rgb));
+        }
+
+        ui.separator();
+        ui.label("curve mode");
+        ui.horizontal(|ui| {
+            let mut is_bspline = matches!(model.curve_mode, CurveMode::BSpline);
+            if ui.radio_value(&mut is_bspline, true, "B-spline").clicked() {
+                model.curve_mode = CurveMode::BSpline;
+            }
+            if ui.radio_value(&mut is_bspline, false, "Chaikin").clicked() {
+                model.curve_mode = CurveMode::Chaikin {
+                    iterations: CHAIKIN_ITERATIONS,
+                };
+            }
+        });
+        if let CurveMode::Chaikin { iterations } = &mut model.curve_mode {
+            ui.label("chaikin iterations");
+            ui.add(egui::Slider::new(iterations, 1..=8));
+        }
+
+        ui.separator();
+        ui.label("octaves");
+        ui.add(egui::Slider::new(&mut model.octaves, 1..=8));
+        ui.label("lacunarity");
+        ui.add(egui::Slider::new(&mut model.lacunarity, 1.0..=4.0));
+        ui.label("gain");
+        ui.add(egui::Slider::new(&mut model.gain, 0.0..=1.0));
+        ui.label("warp strength");
+        ui.add(egui::Slider::new(&mut model.warp_strength, 0.0..=2.0));
+
+        ui.separator();
+        if ui.button("Render high-res").clicked() {
+            model.render_high_res = true;
+        }
+    });
+
+    if model.render_high_res {
+        render_high_res(app, model);
+        model.render_high_res = false;
     }
+
+    render_and_capture_frame(app, model);
 }
 
-fn update(app: &App, model: &mut Model, _update: Update) {
-    let nth = app.elapsed_frames() as f32;
-    model.offset = ease::sine::ease_in_out(nth as f64, 1.0, 4.0, NFRAMES as f64);
+// renders the spline once into `model.texture` (shared by the window view
+// and the gif encoder, rather than drawing it twice) and queues the capture;
+// captures are pipelined (CAPTURE_PIPELINE_DEPTH in flight) instead of
+// blocking the GPU on every single frame
+fn render_and_capture_frame(app: &App, model: &mut Model) {
+    let window = app.main_window();
+    let device = window.swap_chain_device();
+
+    model.draw.reset();
+    model
+        .draw
+        .background()
+        .color(rgb8(BACKGROUND[0], BACKGROUND[1], BACKGROUND[2]));
+    draw_spline(model, &model.draw);
+
+    let ce_desc = wgpu::CommandEncoderDescriptor {
+        label: Some("sand_spline frame render"),
+    };
+    let mut encoder = device.create_command_encoder(&ce_desc);
+    model
+        .renderer
+        .render_to_texture(device, &mut encoder, &model.draw, &model.texture);
+
+    let snapshot = model
+        .texture_capturer
+        .capture(device, &mut encoder, &model.texture);
+    window.swap_chain_queue().submit(Some(encoder.finish()));
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    snapshot
+        .read(move |result| {
+            let image = result.expect("failed to map texture memory").to_owned();
+            tx.send(image).ok();
+        })
+        .unwrap();
+    model.pending_gif_frames.push_back(rx);
+
+    device.poll(wgpu::Maintain::Poll);
+    while model.pending_gif_frames.len() > CAPTURE_PIPELINE_DEPTH {
+        drain_oldest_gif_frame(device, model);
+    }
+}
+
+// blocks on the oldest in-flight capture and appends it to the gif; called
+// both to keep the pipeline bounded and, via `exit`, to flush the tail
+fn drain_oldest_gif_frame(device: &wgpu::Device, model: &mut Model) {
+    let rx = match model.pending_gif_frames.pop_front() {
+        Some(rx) => rx,
+        None => return,
+    };
+    device.poll(wgpu::Maintain::Wait);
+    if let Ok(image) = rx.recv() {
+        let delay = (100 / GIF_FPS as u32) as u16;
+        model.gif.push_frame(&image, &GIF_PALETTE, delay);
+    }
 }
 
-fn view(app: &App, model: &Model, frame: Frame) {
-    let draw = app.draw();
-    frame.clear(rgb8(236, 230, 220));
+// AEye-style 4K texture capture, independent of the windowed preview
+fn render_high_res(app: &App, model: &Model) {
+    let texture_size = [3_840, 2_160];
+    let window = app.main_window();
+    let device = window.swap_chain_device();
+
+    let texture = wgpu::TextureBuilder::new()
+        .size(texture_size)
+        .usage(wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED)
+        .sample_count(1)
+        .format(wgpu::TextureFormat::Rgba16Float)
+        .build(device);
+
+    let draw = nannou::Draw::new();
+    let descriptor = texture.descriptor();
+    let mut renderer =
+        nannou::draw::RendererBuilder::new().build_from_texture_descriptor(device, descriptor);
+
+    draw.background().color(rgb8(BACKGROUND[0], BACKGROUND[1], BACKGROUND[2]));
     draw_spline(model, &draw);
 
-    draw.to_frame(app, &frame).unwrap();
-    app.main_window()
-        .capture_frame(format!("{}/frame-{:04}.png", OUT_DIR, frame.nth()));
+    let ce_desc = wgpu::CommandEncoderDescriptor {
+        label: Some("sand_spline high-res render"),
+    };
+    let mut encoder = device.create_command_encoder(&ce_desc);
+    renderer.render_to_texture(device, &mut encoder, &draw, &texture);
+
+    let texture_capturer = wgpu::TextureCapturer::default();
+    let snapshot = texture_capturer.capture(device, &mut encoder, &texture);
+    window.swap_chain_queue().submit(Some(encoder.finish()));
+
+    snapshot
+        .read(move |result| {
+            let image = result.expect("failed to map texture memory").to_owned();
+            image
+                .save("sand_spline_4k.png")
+                .expect("failed to save texture to png image");
+        })
+        .unwrap();
+
+    device.poll(wgpu::Maintain::Wait);
+}
+
+fn view(_app: &App, model: &Model, frame: Frame) {
+    let mut encoder = frame.command_encoder();
+    model
+        .texture_reshaper
+        .encode_render_pass(frame.texture_view(), &mut *encoder);
+    drop(encoder);
+
+    model.egui.draw_to_frame(&frame).unwrap();
+}
+
+// flush any gif captures still in flight when the sketch stops looping
+fn exit(app: &App, mut model: Model) {
+    let window = app.main_window();
+    let device = window.swap_chain_device();
+    while !model.pending_gif_frames.is_empty() {
+        drain_oldest_gif_frame(device, &mut model);
+    }
 }
 
 fn main() {
-    nannou::app(model).update(update).run();
+    nannou::app(model).update(update).exit(exit).run();
 }